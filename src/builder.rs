@@ -0,0 +1,67 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::LocoError;
+use crate::packet::{create_loco_packet, pad_method_name, RequestLocoHeader};
+
+/// Ties a request type to its wire method name and expected response type,
+/// so `LocoSession::send` can infer both from the request value alone (and
+/// `PacketBuilder::for_method` can take the method name from the type)
+/// instead of a stringly-typed method name scattered through every call site.
+///
+/// ```ignore
+/// impl LocoMethod for CheckinRequest {
+///     const METHOD: &'static str = "CHECKIN";
+///     type Response = CheckinResponse;
+/// }
+/// ```
+pub trait LocoMethod: Serialize {
+    const METHOD: &'static str;
+    type Response: DeserializeOwned;
+}
+
+/// Builds a LOCO request packet without making callers hand-construct a
+/// `RequestLocoHeader`, remember the zero-padded method-name quirk, or call
+/// `bson::to_bson(...).unwrap()` themselves.
+pub struct PacketBuilder {
+    method_name: String,
+    packet_id: u32,
+    status_code: u16,
+}
+
+impl PacketBuilder {
+    pub fn new(method_name: impl Into<String>) -> Result<Self, LocoError> {
+        let method_name = method_name.into();
+        pad_method_name(&method_name)?;
+
+        Ok(Self {
+            method_name,
+            packet_id: 1,
+            status_code: 0,
+        })
+    }
+
+    pub fn for_method<M: LocoMethod>() -> Result<Self, LocoError> {
+        Self::new(M::METHOD)
+    }
+
+    pub fn packet_id(mut self, packet_id: u32) -> Self {
+        self.packet_id = packet_id;
+        self
+    }
+
+    pub fn status_code(mut self, status_code: u16) -> Self {
+        self.status_code = status_code;
+        self
+    }
+
+    pub fn build<T: Serialize>(self, body: &T) -> Result<Vec<u8>, LocoError> {
+        let header = RequestLocoHeader {
+            packet_id: self.packet_id,
+            status_code: self.status_code,
+            method_name: self.method_name,
+            body_type: 0,
+        };
+
+        create_loco_packet(header, bson::to_bson(body)?)
+    }
+}
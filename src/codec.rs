@@ -0,0 +1,73 @@
+use bson::Bson;
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::error::LocoError;
+use crate::packet::{create_loco_packet, parse_loco_packet, RequestLocoHeader, ResponseLocoPacket};
+
+const LOCO_HEADER_SIZE: usize = 22;
+const DEFAULT_MAX_BODY_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// Frames the LOCO wire protocol (header + BSON body) for use with `Framed`,
+/// so a `TcpStream` can be driven as a `Stream`/`Sink` of packets instead of
+/// manually `read_exact`-ing a header then a body.
+pub struct LocoCodec {
+    max_body_length: u32,
+}
+
+impl LocoCodec {
+    pub fn new(max_body_length: u32) -> Self {
+        Self { max_body_length }
+    }
+}
+
+impl Default for LocoCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BODY_LENGTH)
+    }
+}
+
+impl Decoder for LocoCodec {
+    type Item = ResponseLocoPacket<Bson>;
+    type Error = LocoError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < LOCO_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let body_length = u32::from_le_bytes(
+            src[18..LOCO_HEADER_SIZE]
+                .try_into()
+                .map_err(|_| LocoError::MalformedHeader { field: "body_length" })?,
+        );
+        if body_length > self.max_body_length {
+            return Err(LocoError::OversizedBody {
+                body_length,
+                max_body_length: self.max_body_length,
+            });
+        }
+
+        let frame_length = LOCO_HEADER_SIZE + body_length as usize;
+        if src.len() < frame_length {
+            src.reserve(frame_length - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(frame_length);
+        let packet = parse_loco_packet::<Bson>(&frame[..LOCO_HEADER_SIZE], &frame[LOCO_HEADER_SIZE..])?;
+
+        Ok(Some(packet))
+    }
+}
+
+impl Encoder<(RequestLocoHeader, Bson)> for LocoCodec {
+    type Error = LocoError;
+
+    fn encode(&mut self, item: (RequestLocoHeader, Bson), dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let (header, body) = item;
+        dst.extend_from_slice(&create_loco_packet(header, body)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bson::{doc, Bson};
+use bytes::BytesMut;
+use rsa::RsaPublicKey;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::time::{sleep, timeout};
+
+use crate::builder::PacketBuilder;
+use crate::crypto::{LocoCrypto, LocoSecureHeader};
+use crate::error::LocoError;
+use crate::packet::{create_loco_packet, parse_loco_packet, RequestLocoHeader, ResponseLocoPacket};
+
+pub type ConnectionError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Size of a `LocoSecureHeader` on the wire (`data_length: u32` + `iv_key: [u8; 16]`).
+const SECURE_HEADER_SIZE: usize = 20;
+/// Size of the plaintext `RawLocoHeader` once a secure frame is decrypted.
+const LOCO_HEADER_SIZE: usize = 22;
+
+/// Floor for the PING cadence, so a GETCONF-negotiated interval of 0 (or an
+/// interval that was never set) can't turn the keep-alive loop into a busy
+/// loop that floods PINGs and starves every other `drive` branch.
+const MIN_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to PING while foreground/background, and how long to wait
+/// before reconnecting a dropped socket, as negotiated via `GETCONF`'s
+/// `fgPingItv` / `bgPingItv` / `bgReconnItv` / `reqTimeout`.
+#[derive(Clone, Copy, Debug)]
+pub struct KeepAliveIntervals {
+    pub foreground_ping: Duration,
+    pub background_ping: Duration,
+    pub background_reconnect: Duration,
+    pub request_timeout: Duration,
+}
+
+/// Everything needed to (re)establish the encrypted session: where to dial,
+/// the server's RSA key and negotiated algorithms, and the CHECKIN body to
+/// replay after every reconnect.
+pub struct ReconnectConfig {
+    pub addr: String,
+    pub server_public_key: RsaPublicKey,
+    pub rsa_encrypt_type: u32,
+    pub aes_encrypt_type: u32,
+    pub checkin_body: Bson,
+}
+
+struct OutboundRequest {
+    header: RequestLocoHeader,
+    body: Bson,
+    reply_to: oneshot::Sender<Result<ResponseLocoPacket<Bson>, LocoError>>,
+}
+
+type PendingReplies = Arc<Mutex<HashMap<u32, oneshot::Sender<Result<ResponseLocoPacket<Bson>, LocoError>>>>>;
+
+/// A single handle to an encrypted LOCO session that keeps itself alive: a
+/// background task pings on `intervals`, applies `request_timeout` to every
+/// in-flight request, and transparently reconnects (replaying the RSA+AES
+/// handshake and CHECKIN) when the socket drops. Callers keep calling
+/// `request` across reconnects instead of re-running the checkin by hand.
+pub struct LocoConnection {
+    next_packet_id: Arc<AtomicU32>,
+    outbox: mpsc::Sender<OutboundRequest>,
+    foreground: Arc<AtomicBool>,
+    request_timeout: Duration,
+    pending: PendingReplies,
+}
+
+impl LocoConnection {
+    pub fn spawn(
+        config: ReconnectConfig,
+        intervals: KeepAliveIntervals,
+    ) -> (Self, mpsc::Receiver<ResponseLocoPacket<Bson>>) {
+        let (outbox_tx, outbox_rx) = mpsc::channel(32);
+        let (push_tx, push_rx) = mpsc::channel(32);
+        let foreground = Arc::new(AtomicBool::new(true));
+        // Shared with `drive` so PINGs are numbered from the same space as
+        // requests (no separate counter that can collide on packet_id), and
+        // reused across reconnects so a timed-out `request` can prune its
+        // own entry instead of only `run`'s post-disconnect `clear` doing it.
+        let next_packet_id = Arc::new(AtomicU32::new(1));
+        let pending: PendingReplies = Arc::new(Mutex::new(HashMap::new()));
+
+        tokio::spawn(Self::run(
+            config,
+            intervals,
+            outbox_rx,
+            push_tx,
+            foreground.clone(),
+            next_packet_id.clone(),
+            pending.clone(),
+        ));
+
+        let connection = Self {
+            next_packet_id,
+            outbox: outbox_tx,
+            foreground,
+            request_timeout: intervals.request_timeout,
+            pending,
+        };
+
+        (connection, push_rx)
+    }
+
+    /// Switches the PING cadence between `foreground_ping` and
+    /// `background_ping`, e.g. when the app is backgrounded.
+    pub fn set_foreground(&self, foreground: bool) {
+        self.foreground.store(foreground, Ordering::SeqCst);
+    }
+
+    pub async fn request<Req, Res>(&self, method: &str, body: Req) -> Result<Res, ConnectionError>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let packet_id = self.next_packet_id.fetch_add(1, Ordering::SeqCst);
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        let header = RequestLocoHeader {
+            packet_id,
+            status_code: 0,
+            method_name: method.to_string(),
+            body_type: 0,
+        };
+        let body = bson::to_bson(&body)?;
+
+        self.outbox
+            .send(OutboundRequest { header, body, reply_to: reply_tx })
+            .await
+            .map_err(|_| "loco connection task has shut down")?;
+
+        let packet = match timeout(self.request_timeout, reply_rx).await {
+            Ok(reply) => reply
+                .map_err(|_| "loco connection dropped before a reply arrived")??,
+            Err(_) => {
+                // Nobody is coming back for this packet_id; prune it
+                // ourselves instead of leaking the sender in `pending`
+                // until the next reconnect's `clear`.
+                self.pending.lock().await.remove(&packet_id);
+                return Err("loco request timed out".into());
+            }
+        };
+
+        Ok(bson::from_bson(packet.body)?)
+    }
+
+    async fn run(
+        config: ReconnectConfig,
+        intervals: KeepAliveIntervals,
+        mut outbox: mpsc::Receiver<OutboundRequest>,
+        pushes: mpsc::Sender<ResponseLocoPacket<Bson>>,
+        foreground: Arc<AtomicBool>,
+        next_packet_id: Arc<AtomicU32>,
+        pending: PendingReplies,
+    ) {
+        loop {
+            let (stream, crypto, read_buf) = match Self::handshake(&config, &next_packet_id).await {
+                Ok(connected) => connected,
+                Err(_) => {
+                    sleep(intervals.background_reconnect).await;
+                    continue;
+                }
+            };
+
+            Self::drive(
+                stream,
+                crypto,
+                read_buf,
+                &mut outbox,
+                &pending,
+                &pushes,
+                &foreground,
+                &intervals,
+                &next_packet_id,
+            )
+            .await;
+
+            // The socket dropped; whoever was waiting on a reply is out of
+            // luck, and we retry the whole handshake after the negotiated
+            // background-reconnect interval.
+            pending.lock().await.clear();
+            sleep(intervals.background_reconnect).await;
+        }
+    }
+
+    async fn handshake(
+        config: &ReconnectConfig,
+        next_packet_id: &Arc<AtomicU32>,
+    ) -> Result<(TcpStream, LocoCrypto, BytesMut), ConnectionError> {
+        let mut stream = TcpStream::connect(&config.addr).await?;
+        let mut crypto = LocoCrypto::new(
+            config.server_public_key.clone(),
+            config.rsa_encrypt_type,
+            config.aes_encrypt_type,
+        );
+
+        stream.write_all(&crypto.handshake_packet()?).await?;
+        stream.flush().await?;
+
+        // Allocated from the same counter as `request`/PING so CHECKIN's
+        // packet_id can't collide with either, instead of reusing
+        // `PacketBuilder`'s default of 1.
+        let checkin_id = next_packet_id.fetch_add(1, Ordering::SeqCst);
+        let checkin_buffer = PacketBuilder::new("CHECKIN")
+            .expect("CHECKIN fits the 11-byte wire method-name field")
+            .packet_id(checkin_id)
+            .build(&config.checkin_body)?;
+        stream.write_all(&crypto.encrypt(&checkin_buffer)?).await?;
+        stream.flush().await?;
+
+        // Bytes read past the CHECKIN reply (a second frame already queued
+        // on the socket) belong to `drive`, so the buffer travels with the
+        // connection instead of being discarded here.
+        let mut read_buf = BytesMut::new();
+        Self::read_secure_packet(&mut stream, &crypto, &mut read_buf).await?;
+
+        Ok((stream, crypto, read_buf))
+    }
+
+    /// Tries to pull one complete secure frame (`LocoSecureHeader` +
+    /// ciphertext) out of bytes already sitting in `buf`, the same
+    /// length-then-wait-for-more shape `LocoCodec::decode` uses: a short
+    /// header or body returns `Ok(None)` instead of slicing or subtracting
+    /// blindly, and a `data_length` that can't even hold the 16-byte IV is a
+    /// `LocoError` rather than an underflow panic.
+    fn decode_secure_packet(
+        crypto: &LocoCrypto,
+        buf: &mut BytesMut,
+    ) -> Result<Option<ResponseLocoPacket<Bson>>, LocoError> {
+        if buf.len() < SECURE_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let secure_header: LocoSecureHeader = bincode::deserialize(&buf[..SECURE_HEADER_SIZE])?;
+        let ciphertext_length = secure_header
+            .data_length
+            .checked_sub(16)
+            .ok_or(LocoError::MalformedSecureHeader { data_length: secure_header.data_length })?
+            as usize;
+
+        let frame_length = SECURE_HEADER_SIZE + ciphertext_length;
+        if buf.len() < frame_length {
+            buf.reserve(frame_length - buf.len());
+            return Ok(None);
+        }
+
+        let frame = buf.split_to(frame_length);
+        let decrypted = crypto.decrypt(&secure_header, &frame[SECURE_HEADER_SIZE..])?;
+        if decrypted.len() < LOCO_HEADER_SIZE {
+            return Err(LocoError::MalformedSecureHeader { data_length: secure_header.data_length });
+        }
+
+        Ok(Some(parse_loco_packet::<Bson>(
+            &decrypted[..LOCO_HEADER_SIZE],
+            &decrypted[LOCO_HEADER_SIZE..],
+        )?))
+    }
+
+    /// Reads one secure packet off `stream`, accumulating into `buf` across
+    /// calls instead of a per-call `read_exact`. `AsyncReadExt::read_buf` is
+    /// cancel-safe: when this future races `outbox.recv()`/`sleep` inside
+    /// `tokio::select!` and loses, whatever was already read stays in `buf`
+    /// for the next call to pick up, so the encrypted stream never desyncs
+    /// mid-frame.
+    async fn read_secure_packet(
+        stream: &mut TcpStream,
+        crypto: &LocoCrypto,
+        buf: &mut BytesMut,
+    ) -> Result<ResponseLocoPacket<Bson>, LocoError> {
+        loop {
+            if let Some(packet) = Self::decode_secure_packet(crypto, buf)? {
+                return Ok(packet);
+            }
+
+            let read = stream.read_buf(buf).await?;
+            if read == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn drive(
+        mut stream: TcpStream,
+        mut crypto: LocoCrypto,
+        mut read_buf: BytesMut,
+        outbox: &mut mpsc::Receiver<OutboundRequest>,
+        pending: &PendingReplies,
+        pushes: &mpsc::Sender<ResponseLocoPacket<Bson>>,
+        foreground: &Arc<AtomicBool>,
+        intervals: &KeepAliveIntervals,
+        next_packet_id: &Arc<AtomicU32>,
+    ) {
+        loop {
+            // A GETCONF-negotiated interval of 0 (or an unset one) would
+            // otherwise make the `sleep` branch below complete instantly on
+            // every iteration, flooding PINGs and starving the outbox/read
+            // branches; floor it at a sane minimum instead.
+            let ping_interval = if foreground.load(Ordering::SeqCst) {
+                intervals.foreground_ping
+            } else {
+                intervals.background_ping
+            }
+            .max(MIN_PING_INTERVAL);
+
+            tokio::select! {
+                outbound = outbox.recv() => {
+                    let Some(outbound) = outbound else { return };
+                    let packet_id = outbound.header.packet_id;
+                    let encrypted: Result<Vec<u8>, LocoError> = create_loco_packet(outbound.header, outbound.body)
+                        .and_then(|buf| crypto.encrypt(&buf));
+                    let encrypted = match encrypted {
+                        Ok(encrypted) => encrypted,
+                        Err(err) => {
+                            let _ = outbound.reply_to.send(Err(err));
+                            continue;
+                        }
+                    };
+
+                    pending.lock().await.insert(packet_id, outbound.reply_to);
+                    if stream.write_all(&encrypted).await.is_err() {
+                        return;
+                    }
+                }
+                _ = sleep(ping_interval) => {
+                    // PINGs are allocated from the same counter as `request`
+                    // (not a separate space starting at 0), so a PING reply
+                    // can never collide with, and get delivered to, an
+                    // unrelated in-flight request's packet_id.
+                    let ping_id = next_packet_id.fetch_add(1, Ordering::SeqCst);
+                    let encrypted: Result<Vec<u8>, LocoError> = create_loco_packet(RequestLocoHeader {
+                        packet_id: ping_id,
+                        status_code: 0,
+                        method_name: "PING".to_string(),
+                        body_type: 0,
+                    }, Bson::Document(doc! {})).and_then(|buf| crypto.encrypt(&buf));
+
+                    if let Ok(ping_buffer) = encrypted {
+                        if stream.write_all(&ping_buffer).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                packet = Self::read_secure_packet(&mut stream, &crypto, &mut read_buf) => {
+                    let Ok(packet) = packet else { return };
+                    let waiting = pending.lock().await.remove(&packet.header.packet_id);
+                    match waiting {
+                        Some(reply_to) => { let _ = reply_to.send(Ok(packet)); }
+                        None => { let _ = pushes.send(packet).await; }
+                    }
+                }
+            }
+
+            if crypto.should_rekey() {
+                match crypto.rekey() {
+                    Ok(rekey_packet) => {
+                        if stream.write_all(&rekey_packet).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+}
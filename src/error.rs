@@ -0,0 +1,86 @@
+use std::fmt;
+
+/// Errors that can arise anywhere in the LOCO framing, crypto, or
+/// connection-management code. Replaces the `unwrap()`s that used to panic
+/// the whole process on a single malformed frame from the server.
+#[derive(Debug)]
+pub enum LocoError {
+    Io(std::io::Error),
+    Bincode(bincode::Error),
+    Bson(bson::de::Error),
+    BsonSer(bson::ser::Error),
+    Utf8(std::string::FromUtf8Error),
+    MethodNameTooLong { method: String, max_len: usize },
+    OversizedBody { body_length: u32, max_body_length: u32 },
+    MalformedSecureHeader { data_length: u32 },
+    MalformedHeader { field: &'static str },
+    Crypto(String),
+    HandshakeMismatch(String),
+    External(String),
+}
+
+impl fmt::Display for LocoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocoError::Io(err) => write!(f, "loco io error: {err}"),
+            LocoError::Bincode(err) => write!(f, "loco header (de)serialization failed: {err}"),
+            LocoError::Bson(err) => write!(f, "loco bson decode failed: {err}"),
+            LocoError::BsonSer(err) => write!(f, "loco bson encode failed: {err}"),
+            LocoError::Utf8(err) => write!(f, "loco method name was not valid utf8: {err}"),
+            LocoError::MethodNameTooLong { method, max_len } => write!(
+                f,
+                "method name '{method}' does not fit the {max_len}-byte wire field"
+            ),
+            LocoError::OversizedBody { body_length, max_body_length } => write!(
+                f,
+                "loco body_length {body_length} exceeds max_body_length {max_body_length}"
+            ),
+            LocoError::MalformedSecureHeader { data_length } => write!(
+                f,
+                "loco secure header data_length {data_length} is too short to hold a 16-byte IV and a LOCO header"
+            ),
+            LocoError::MalformedHeader { field } => write!(f, "loco header field '{field}' was malformed"),
+            LocoError::Crypto(msg) => write!(f, "loco crypto failure: {msg}"),
+            LocoError::HandshakeMismatch(msg) => write!(f, "loco handshake mismatch: {msg}"),
+            LocoError::External(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for LocoError {}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for LocoError {
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        LocoError::External(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for LocoError {
+    fn from(err: std::io::Error) -> Self {
+        LocoError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for LocoError {
+    fn from(err: bincode::Error) -> Self {
+        LocoError::Bincode(err)
+    }
+}
+
+impl From<bson::de::Error> for LocoError {
+    fn from(err: bson::de::Error) -> Self {
+        LocoError::Bson(err)
+    }
+}
+
+impl From<bson::ser::Error> for LocoError {
+    fn from(err: bson::ser::Error) -> Self {
+        LocoError::BsonSer(err)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for LocoError {
+    fn from(err: std::string::FromUtf8Error) -> Self {
+        LocoError::Utf8(err)
+    }
+}
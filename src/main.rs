@@ -1,13 +1,23 @@
-use bson::{doc, Bson};
-use bytes::Buf;
-use libaes::Cipher;
-use rand::{thread_rng, RngCore};
-use rsa::{RsaPublicKey, PublicKey, PaddingScheme, pkcs8::DecodePublicKey};
-use serde::{Serialize, Deserialize, de::DeserializeOwned};
-use tokio::{net::TcpStream, io::{BufStream, AsyncWriteExt, AsyncReadExt}};
+use rsa::{RsaPublicKey, pkcs8::DecodePublicKey};
+use serde::{Serialize, Deserialize};
+use tokio::{net::TcpStream, io::BufStream};
 use tokio_native_tls::{native_tls};
 use tokio_util::compat::TokioAsyncReadCompatExt;
 
+mod builder;
+mod codec;
+mod connection;
+mod crypto;
+mod error;
+mod packet;
+mod session;
+
+use builder::LocoMethod;
+use codec::LocoCodec;
+use connection::{KeepAliveIntervals, LocoConnection, ReconnectConfig};
+use error::LocoError;
+use session::LocoSession;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ConnectionInfo {
     #[serde(rename = "bgKeepItv")]
@@ -35,6 +45,17 @@ struct ConnectionInfo {
     ports: Vec<i32>
 }
 
+impl From<&ConnectionInfo> for KeepAliveIntervals {
+    fn from(info: &ConnectionInfo) -> Self {
+        Self {
+            foreground_ping: std::time::Duration::from_secs(info.ping_interval.max(0) as u64),
+            background_ping: std::time::Duration::from_secs(info.background_interval.max(0) as u64),
+            background_reconnect: std::time::Duration::from_secs(info.background_reconnect_interval.max(0) as u64),
+            request_timeout: std::time::Duration::from_secs(info.request_timeout.max(0) as u64),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct HostInfo {
     ssl: Vec<String>,
@@ -122,38 +143,6 @@ struct CheckinResponse {
     vssport: u32,
 }
 
-struct RequestLocoHeader {
-    packet_id: u32,
-    status_code: u16,
-    method_name: String,
-    body_type: u8
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct RawLocoHeader {
-    packet_id: u32,
-    status_code: u16,
-    method_name: [u8; 11],
-    body_type: u8,
-    body_length: u32
-}
-
-#[derive(Debug)]
-struct ResponseLocoHeader {
-    packet_id: u32,
-    status_code: u16,
-    method_name: String,
-    body_type: u8,
-    body_length: u32
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct LocoHandshakeHeader {
-    data_length: u32,
-    rsa_encrypt_type: u32,
-    aes_encrypt_type: u32
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 struct CheckinRequest {
     #[serde(rename="userId")]
@@ -167,10 +156,9 @@ struct CheckinRequest {
     mccmnc: String
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct LocoSecureHeader {
-    data_length: u32,
-    iv_key: [u8; 16],
+impl LocoMethod for CheckinRequest {
+    const METHOD: &'static str = "CHECKIN";
+    type Response = CheckinResponse;
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -181,156 +169,81 @@ struct BookingRequest {
     mccmnc: String
 }
 
-struct ResponseLocoPacket<T> {
-    header: ResponseLocoHeader, // TODO: LocoHeader로 분리
-    body: T
-}
-
-fn parse_loco_header(header_buffer: &[u8]) -> ResponseLocoHeader {
-    let raw_header: RawLocoHeader = bincode::deserialize(header_buffer).unwrap();
-    let method_name = String::from_utf8(raw_header.method_name.to_vec()).unwrap().replace("\0", "");
-    let response_header = ResponseLocoHeader {
-        packet_id: raw_header.packet_id,
-        status_code: raw_header.status_code,
-        method_name: method_name,
-        body_type: raw_header.body_type,
-        body_length: raw_header.body_length
-    };
-
-    response_header
-}
-
-fn parse_loco_packet<T: DeserializeOwned>(header_buffer: &[u8], data_buffer: &[u8]) -> ResponseLocoPacket<T> {
-    let response_header = parse_loco_header(header_buffer);
-    let body: T = bson::from_bson(bson::Bson::Document(bson::from_slice(&*data_buffer).unwrap())).unwrap();
-
-    ResponseLocoPacket {
-        header: response_header,
-        body
-    }
-}
-
-fn create_loco_raw_header(header: RequestLocoHeader, body_length: u32) -> RawLocoHeader {
-    let raw_loco_header = RawLocoHeader {
-        packet_id: header.packet_id,
-        status_code: header.status_code,
-        method_name: (&*[header.method_name.as_bytes(), &([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0][..(11 - header.method_name.len())])].concat())[..11].try_into().unwrap(),
-        body_type: header.body_type,
-        body_length: body_length
-    };
-
-    raw_loco_header
+impl LocoMethod for BookingRequest {
+    const METHOD: &'static str = "GETCONF";
+    type Response = GetConfRes;
 }
 
-fn create_loco_packet(header: RequestLocoHeader, body: Bson) -> Vec<u8> {
-    let body_vec = bytes::BytesMut::from(&*bson::to_vec(&body).unwrap());
-    let raw_loco_header = create_loco_raw_header(header, body_vec.len() as u32);
-    let loco_header_vec = bincode::serialize(&raw_loco_header).unwrap();
-
-    [loco_header_vec, body_vec.to_vec()].concat()
-}
-
-async fn get_booking_data() {
-    let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new().unwrap());
+async fn get_booking_data() -> Result<GetConfRes, LocoError> {
+    let connector = tokio_native_tls::TlsConnector::from(
+        native_tls::TlsConnector::new().map_err(|err| LocoError::External(err.to_string()))?,
+    );
     let connection = connector
         .connect(
             "booking-loco.kakao.com",
-            BufStream::new(
-                TcpStream::connect("booking-loco.kakao.com:443")
-                    .await.unwrap(),
-            )
+            BufStream::new(TcpStream::connect("booking-loco.kakao.com:443").await?),
         )
-        .await.unwrap()
+        .await
+        .map_err(|err| LocoError::External(err.to_string()))?
         .compat();
 
-    let mut stream = connection.into_inner();
-    let request_buffer = create_loco_packet(
-        RequestLocoHeader {
-            packet_id: 1,
-            status_code: 0,
-            method_name: "GETCONF".to_string(),
-            body_type: 0
-        }, bson::to_bson(&BookingRequest {
+    let stream = connection.into_inner();
+    let (session, _pushes) = LocoSession::new(stream, LocoCodec::default());
+
+    let response = session
+        .send(BookingRequest {
             model: "".to_string(),
             os: "".to_string(),
-            mccmnc: "".to_string()
-        }).unwrap());
-
-    stream.write(&request_buffer).await.unwrap();
-    stream.flush().await.unwrap();
+            mccmnc: "".to_string(),
+        })
+        .await?;
+    println!("{:?}", response);
 
-    let mut header_buffer = [0; 22];
-    stream.read_exact(&mut header_buffer).await.unwrap();
-    let loco_header = parse_loco_header(&header_buffer);
-
-    let mut data_buffer = vec![0; loco_header.body_length.try_into().unwrap()];
-    stream.read_exact(&mut data_buffer).await.unwrap();
-
-    let response_packet = parse_loco_packet::<GetConfRes>(&header_buffer, &data_buffer);
-    println!("{:?} {:?}", response_packet.header, response_packet.body);;
+    Ok(response)
 }
 
-async fn get_checkin_data() {
-    let mut stream = TcpStream::connect("ticket-loco.kakao.com:443").await.unwrap();
-    let mut rng = thread_rng();
-
-    let mut aes_key = [0; 16];
-    rng.fill_bytes(&mut aes_key);
-
+/// Checks in over a `LocoConnection` (rather than hand-rolling the
+/// handshake/read loop) so the keep-alive PINGs, `request_timeout`, and
+/// auto-reconnect the connection manager provides are actually exercised,
+/// using `intervals` negotiated by a prior `get_booking_data` GETCONF call.
+async fn get_checkin_data(intervals: KeepAliveIntervals) -> Result<(), LocoError> {
     let pem_key = "-----BEGIN PUBLIC KEY-----\nMIIBIDANBgkqhkiG9w0BAQEFAAOCAQ0AMIIBCAKCAQEA52Y1NVBfNkzCmnggwVwScdUO7enyo/RtnSsr8io+8cQrhXlsi1Msn8yGQv+JW9AZKyetYeYl/BuCFS7liJixwJ1UFkH7J0m8GRGNH4VRuRMJa97WfvVpsMr1cIaFnoCeRwvvaaqw9/ikWFWw/Cq6ieAsO80pRCcAVh1mCytDUmeqykuz6TYwldTaYbpHO8u48d3jvUXveSv5J9t40GiaMdyVRZpx7LY2M0ZsjjbQXRe8ziXtGEq/8Gk0vkV2BnRk/v6uce8k5ERCWGyVHRaRo6FJljYNvaIoBBx2WGJVbb6fXCLlkPFlH/A9tGZ0fxNDuomZWwnF+EDIDsq5R/G8+wIBAw==\n-----END PUBLIC KEY-----";
-    let pub_key = RsaPublicKey::from_public_key_der(&pem::parse(pem_key).unwrap().contents).unwrap();
-    let encrypted_aes_key = pub_key.encrypt(&mut rng, PaddingScheme::new_oaep::<sha1::Sha1>(), &aes_key).unwrap().to_vec();
-    let handshake_packet = LocoHandshakeHeader { data_length: encrypted_aes_key.len() as u32, rsa_encrypt_type: 14, aes_encrypt_type: 2 };
-    let handshake_buffer = [bincode::serialize(&handshake_packet).unwrap(), encrypted_aes_key].concat();
-
-    stream.write(&handshake_buffer).await.unwrap();
-    stream.flush().await.unwrap();
-
-    let request_buffer = create_loco_packet(
-        RequestLocoHeader {
-            packet_id: 1,
-            status_code: 0,
-            method_name: "CHECKIN".to_string(),
-            body_type: 0
-        }, bson::to_bson(&CheckinRequest {
-            user_id: 1,
-            os: "android".to_string(),
-            ntype: 0,
-            app_ver: "9.7.2".to_string(),
-            lang: "ko".to_string(),
-            mccmnc: "45005".to_string()
-        }).unwrap());
-
-    let aes_cipher = Cipher::new_128(&aes_key);
-    let mut iv_key = [0; 16];
-    rng.fill_bytes(&mut iv_key);
-
-    let encrypted_aes_data = aes_cipher.cfb128_encrypt(&iv_key, &request_buffer);
-    let secure_data_length = (encrypted_aes_data.len() + 16) as u32;
-    let secure_packet = LocoSecureHeader { data_length: secure_data_length, iv_key };
-    let secure_buffer = [bincode::serialize(&secure_packet).unwrap(), encrypted_aes_data].concat();
-    
-    stream.write(&secure_buffer).await.unwrap();    
-    stream.flush().await.unwrap();
+    let pub_key = RsaPublicKey::from_public_key_der(
+        &pem::parse(pem_key).map_err(|err| LocoError::External(err.to_string()))?.contents,
+    )
+    .map_err(|err| LocoError::External(err.to_string()))?;
+
+    let checkin_request = CheckinRequest {
+        user_id: 1,
+        os: "android".to_string(),
+        ntype: 0,
+        app_ver: "9.7.2".to_string(),
+        lang: "ko".to_string(),
+        mccmnc: "45005".to_string(),
+    };
+    let checkin_body = bson::to_bson(&checkin_request)?;
+
+    let config = ReconnectConfig {
+        addr: "ticket-loco.kakao.com:443".to_string(),
+        server_public_key: pub_key,
+        rsa_encrypt_type: 14,
+        aes_encrypt_type: 2,
+        checkin_body,
+    };
 
-    let mut header_buffer = [0; 20];
-    stream.read_exact(&mut header_buffer).await.unwrap();
+    let (connection, _pushes) = LocoConnection::spawn(config, intervals);
 
-    let mut bytes = bytes::BytesMut::from(&header_buffer[..]);
-    let size = bytes.get_u32_le() as usize - 16;
-    let mut data_buffer = vec![0; size];
-    stream.read_exact(&mut data_buffer).await.unwrap();
-    
-    let decrypted_buffer = aes_cipher.cfb128_decrypt(&header_buffer[4..20], &data_buffer);
-    let header_buffer = &decrypted_buffer[0..22];
-    let data_buffer = &decrypted_buffer[22..];
+    let response: CheckinResponse = connection.request("CHECKIN", checkin_request).await?;
+    println!("{:?}", response);
 
-    let response_packet = parse_loco_packet::<CheckinResponse>(header_buffer, data_buffer);
-    println!("{:?} {:?}", response_packet.header, response_packet.body);
+    Ok(())
 }
 
 #[tokio::main]
-async fn main() {
-    get_booking_data().await;
-    get_checkin_data().await;
+async fn main() -> Result<(), LocoError> {
+    let booking = get_booking_data().await?;
+    let intervals = KeepAliveIntervals::from(&booking.wifi);
+    get_checkin_data(intervals).await?;
+
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+use bson::Bson;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::error::LocoError;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawLocoHeader {
+    pub packet_id: u32,
+    pub status_code: u16,
+    pub method_name: [u8; 11],
+    pub body_type: u8,
+    pub body_length: u32,
+}
+
+pub struct RequestLocoHeader {
+    pub packet_id: u32,
+    pub status_code: u16,
+    pub method_name: String,
+    pub body_type: u8,
+}
+
+#[derive(Debug)]
+pub struct ResponseLocoHeader {
+    pub packet_id: u32,
+    pub status_code: u16,
+    pub method_name: String,
+    pub body_type: u8,
+    pub body_length: u32,
+}
+
+pub struct ResponseLocoPacket<T> {
+    pub header: ResponseLocoHeader, // TODO: LocoHeader로 분리
+    pub body: T,
+}
+
+pub fn parse_loco_header(header_buffer: &[u8]) -> Result<ResponseLocoHeader, LocoError> {
+    let raw_header: RawLocoHeader = bincode::deserialize(header_buffer)?;
+    let method_name = String::from_utf8(raw_header.method_name.to_vec())?.replace('\0', "");
+
+    Ok(ResponseLocoHeader {
+        packet_id: raw_header.packet_id,
+        status_code: raw_header.status_code,
+        method_name,
+        body_type: raw_header.body_type,
+        body_length: raw_header.body_length,
+    })
+}
+
+pub fn parse_loco_packet<T: DeserializeOwned>(
+    header_buffer: &[u8],
+    data_buffer: &[u8],
+) -> Result<ResponseLocoPacket<T>, LocoError> {
+    let response_header = parse_loco_header(header_buffer)?;
+    let body: T = bson::from_bson(Bson::Document(bson::from_slice(data_buffer)?))?;
+
+    Ok(ResponseLocoPacket {
+        header: response_header,
+        body,
+    })
+}
+
+pub(crate) const METHOD_NAME_LEN: usize = 11;
+
+/// Zero-pads `method_name` to the fixed 11-byte wire field, the single place
+/// that quirk is implemented instead of being re-derived at every call site.
+pub(crate) fn pad_method_name(method_name: &str) -> Result<[u8; METHOD_NAME_LEN], LocoError> {
+    let bytes = method_name.as_bytes();
+    if bytes.len() > METHOD_NAME_LEN {
+        return Err(LocoError::MethodNameTooLong {
+            method: method_name.to_string(),
+            max_len: METHOD_NAME_LEN,
+        });
+    }
+
+    let mut padded = [0u8; METHOD_NAME_LEN];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    Ok(padded)
+}
+
+pub fn create_loco_raw_header(header: RequestLocoHeader, body_length: u32) -> Result<RawLocoHeader, LocoError> {
+    Ok(RawLocoHeader {
+        packet_id: header.packet_id,
+        status_code: header.status_code,
+        method_name: pad_method_name(&header.method_name)?,
+        body_type: header.body_type,
+        body_length,
+    })
+}
+
+pub fn create_loco_packet(header: RequestLocoHeader, body: Bson) -> Result<Vec<u8>, LocoError> {
+    let body_vec = bytes::BytesMut::from(&*bson::to_vec(&body)?);
+    let raw_loco_header = create_loco_raw_header(header, body_vec.len() as u32)?;
+    let loco_header_vec = bincode::serialize(&raw_loco_header)?;
+
+    Ok([loco_header_vec, body_vec.to_vec()].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_short_method_names_with_zero_bytes() {
+        let padded = pad_method_name("PING").unwrap();
+        assert_eq!(&padded, b"PING\0\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn accepts_method_names_at_the_wire_limit() {
+        let padded = pad_method_name("ELEVENBYTEZ").unwrap();
+        assert_eq!(&padded, b"ELEVENBYTEZ");
+    }
+
+    #[test]
+    fn rejects_method_names_longer_than_the_wire_field() {
+        assert!(pad_method_name("TOOLONGMETHODNAME").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use bson::Bson;
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio_util::codec::Framed;
+
+use crate::builder::LocoMethod;
+use crate::codec::LocoCodec;
+use crate::packet::{RequestLocoHeader, ResponseLocoPacket};
+
+pub type SessionError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Size of the channel used to forward unsolicited (server-pushed) packets
+/// to whoever is listening; pushes are dropped if nobody is subscribed.
+const PUSH_CHANNEL_SIZE: usize = 32;
+
+type PendingReplies = Arc<Mutex<HashMap<u32, oneshot::Sender<ResponseLocoPacket<Bson>>>>>;
+
+/// A long-lived LOCO connection that correlates requests to responses by
+/// `packet_id`, so many requests can be in flight on the same socket and
+/// unsolicited server pushes don't get mistaken for a reply.
+pub struct LocoSession {
+    sink: Mutex<SplitSink<Framed<TcpStream, LocoCodec>, (RequestLocoHeader, Bson)>>,
+    next_packet_id: AtomicU32,
+    pending: PendingReplies,
+}
+
+impl LocoSession {
+    /// Wraps `stream` in `codec` and spawns the background task that reads
+    /// frames off it, handing each one to the `request` call waiting on its
+    /// `packet_id` or, if nobody is waiting, forwarding it on the returned
+    /// channel as a server push.
+    pub fn new(stream: TcpStream, codec: LocoCodec) -> (Self, mpsc::Receiver<ResponseLocoPacket<Bson>>) {
+        let (sink, stream) = Framed::new(stream, codec).split();
+        let pending: PendingReplies = Arc::new(Mutex::new(HashMap::new()));
+        let (push_tx, push_rx) = mpsc::channel(PUSH_CHANNEL_SIZE);
+
+        tokio::spawn(Self::read_loop(stream, pending.clone(), push_tx));
+
+        let session = Self {
+            sink: Mutex::new(sink),
+            next_packet_id: AtomicU32::new(1),
+            pending,
+        };
+
+        (session, push_rx)
+    }
+
+    async fn read_loop(
+        mut stream: SplitStream<Framed<TcpStream, LocoCodec>>,
+        pending: PendingReplies,
+        pushes: mpsc::Sender<ResponseLocoPacket<Bson>>,
+    ) {
+        while let Some(frame) = stream.next().await {
+            let packet = match frame {
+                Ok(packet) => packet,
+                Err(_) => break,
+            };
+
+            let waiting = pending.lock().await.remove(&packet.header.packet_id);
+            match waiting {
+                Some(reply_to) => {
+                    let _ = reply_to.send(packet);
+                }
+                None => {
+                    let _ = pushes.send(packet).await;
+                }
+            }
+        }
+
+        // The stream ended (EOF or a codec error); dropping every waiting
+        // sender here closes their `oneshot::Receiver`s so `request` reports
+        // a closed-connection error instead of hanging forever.
+        pending.lock().await.clear();
+    }
+
+    /// Sends `body` under `method` with a freshly assigned `packet_id` and
+    /// awaits the matching reply, however many other requests are in flight.
+    pub async fn request<Req, Res>(&self, method: &str, body: Req) -> Result<Res, SessionError>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let packet_id = self.next_packet_id.fetch_add(1, Ordering::SeqCst);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.pending.lock().await.insert(packet_id, reply_tx);
+
+        let header = RequestLocoHeader {
+            packet_id,
+            status_code: 0,
+            method_name: method.to_string(),
+            body_type: 0,
+        };
+        let body = bson::to_bson(&body)?;
+
+        if let Err(err) = self.sink.lock().await.send((header, body)).await {
+            self.pending.lock().await.remove(&packet_id);
+            return Err(Box::new(err));
+        }
+
+        let packet = reply_rx
+            .await
+            .map_err(|_| "loco session closed before a reply arrived")?;
+
+        Ok(bson::from_bson(packet.body)?)
+    }
+
+    /// Like `request`, but infers the method name and response type from
+    /// `M: LocoMethod` instead of taking them separately.
+    pub async fn send<M: LocoMethod>(&self, body: M) -> Result<M::Response, SessionError> {
+        self.request(M::METHOD, body).await
+    }
+}